@@ -1,10 +1,12 @@
-use std::{collections::{HashMap, HashSet}, env, fs::File, path::Path};
+use std::{collections::{HashMap, HashSet}, env, fs, fs::File, path::Path};
 
 use iri_s::IriS;
-use oxrdf::{IriParseError, NamedNode, Subject, Term};
+use oxigraph::sparql::{QueryResults, SparqlEvaluator};
+use oxigraph::store::Store;
+use oxrdf::{GraphName, IriParseError, NamedNode, NamedOrBlankNode, Quad, Term};
 use prefixmap::{PrefixMap, PrefixMapError};
 use serde::Serialize;
-use srdf::{SRDFBasic, SRDFGraph, SRDFGraphError, SRDF};
+use srdf::{NeighsRDF, SRDFGraph, SRDFGraphError};
 
 #[derive(Serialize)]
 struct Matches {
@@ -29,36 +31,341 @@ enum SubjectType {
     ObjectProperty
 }
 
-fn find_subjects(graph: &SRDFGraph, pred: &<SRDFGraph as SRDFBasic>::IRI, object: &<SRDFGraph as SRDFBasic>::Term) -> Result<Vec<Item>, AppError> {
-    let pm = PrefixMap::from_hashmap(&HashMap::from([
-        ("iof-core", "https://spec.industrialontologies.org/ontology/core/Core/"),
-        ("owl", "http://www.w3.org/2002/07/owl#"),
-        ("bfo", "http://purl.obolibrary.org/obo/"),
-    ]))?;
-    let rdfs_label = NamedNode::new("http://www.w3.org/2000/01/rdf-schema#label")?;
+// Fallback order for the label predicate, overridden by `--label-predicate`.
+fn default_label_predicates() -> Vec<String> {
+    vec![
+        "http://www.w3.org/2000/01/rdf-schema#label".to_string(),
+        "http://www.w3.org/2004/02/skos/core#prefLabel".to_string(),
+        "https://spec.industrialontologies.org/ontology/core/Core/synonym".to_string(),
+    ]
+}
 
+fn find_subjects(
+    graph: &SRDFGraph,
+    pred: &NamedNode,
+    object: &Term,
+    pm: &PrefixMap,
+    label_predicates: &[String],
+) -> Result<Vec<Item>, AppError> {
+    let label_predicates: Vec<NamedNode> = label_predicates
+        .iter()
+        .map(NamedNode::new)
+        .collect::<Result<_, _>>()?;
+
+    let mut seen: HashSet<NamedOrBlankNode> = HashSet::new();
     let mut items: Vec<Item> = Vec::new();
-    for subject in graph.subjects_with_predicate_object(pred, object)? {
-        let labels = graph.objects_for_subject_predicate(&subject, &rdfs_label)?;
-        let english_label = get_english_label(&labels);
+    for triple in graph.triples_with_predicate_object(pred.clone(), object.clone())? {
+        if !seen.insert(triple.subject.clone()) {
+            continue;
+        }
+
+        let english_label = find_preferred_label(graph, &triple.subject, &label_predicates)?;
 
-        let subj_iri = match subject {
-            oxrdf::Subject::NamedNode(named_node) => IriS::from_named_node(&named_node),
-            _ => continue
+        let subj_iri = match triple.subject {
+            NamedOrBlankNode::NamedNode(named_node) => IriS::from(named_node),
+            NamedOrBlankNode::BlankNode(_) => continue,
         };
         let qualified_name = pm.qualify(&subj_iri);
 
         items.push(Item {
-            qualified_name: qualified_name.clone(),
-            english_label: match english_label {
-                Some(l) => Some(l.to_string()),
-                None => None,
-            },
+            qualified_name,
+            english_label,
         });
     }
     Ok(items)
 }
 
+// Try each label predicate, in order, until one has an English-tagged value.
+fn find_preferred_label(graph: &SRDFGraph, subject: &NamedOrBlankNode, label_predicates: &[NamedNode]) -> Result<Option<String>, AppError> {
+    for predicate in label_predicates {
+        let labels: HashSet<Term> = graph
+            .triples_with_subject_predicate(subject.clone(), predicate.clone())?
+            .map(|triple| triple.object)
+            .collect();
+        if let Some(label) = get_english_label(&labels) {
+            return Ok(Some(label.to_string()));
+        }
+    }
+    Ok(None)
+}
+
+// Run a SPARQL SELECT query against the graph and turn each solution into a
+// MatchItem directly, using the bound `?trigger`, `?replace` and `?label`
+// variables for the corresponding espanso fields. This is the generic
+// counterpart to `find_subjects`/`build_matches`: instead of us deciding
+// what a trigger looks like, the query author does.
+//
+// `SRDFGraph::query_select` only ever queries its own `store` field, which
+// stays `None` for every graph built through `SRDFGraph::from_path`/`merge_from_path`
+// (nothing in srdf's public API populates it) — so we load the graph's
+// triples into our own in-memory oxigraph `Store` and query that directly
+// instead of going through srdf's `QueryRDF` impl.
+fn find_matches_sparql(graph: &SRDFGraph, query: &str) -> Result<Vec<MatchItem>, AppError> {
+    let store = Store::new().map_err(|why| AppError::SparqlQueryError(why.to_string()))?;
+    for triple in graph.triples()? {
+        store
+            .insert(&Quad::new(triple.subject, triple.predicate, triple.object, GraphName::DefaultGraph))
+            .map_err(|why| AppError::SparqlQueryError(why.to_string()))?;
+    }
+
+    let results = SparqlEvaluator::new()
+        .parse_query(query)
+        .map_err(|why| AppError::SparqlQueryError(why.to_string()))?
+        .on_store(&store)
+        .execute()
+        .map_err(|why| AppError::SparqlQueryError(why.to_string()))?;
+
+    let solutions = match results {
+        QueryResults::Solutions(solutions) => solutions,
+        _ => return Err(AppError::SparqlQueryError("expected a SELECT query".to_string())),
+    };
+
+    // A literal contributes its lexical value and an IRI its plain string,
+    // not their N-Triples form (quotes, language tag, angle brackets).
+    let term_value = |term: &Term| match term {
+        Term::Literal(literal) => literal.value().to_string(),
+        Term::NamedNode(node) => node.as_str().to_string(),
+        other => other.to_string(),
+    };
+
+    let mut match_items: Vec<MatchItem> = Vec::new();
+    for solution in solutions {
+        let solution = solution.map_err(|why| AppError::SparqlQueryError(why.to_string()))?;
+        let trigger = solution
+            .get("trigger")
+            .map(term_value)
+            .ok_or(AppError::SparqlMissingBinding("trigger"))?;
+        let replace = solution
+            .get("replace")
+            .map(term_value)
+            .ok_or(AppError::SparqlMissingBinding("replace"))?;
+        let label = solution
+            .get("label")
+            .map(term_value)
+            .unwrap_or_else(|| replace.clone());
+
+        match_items.push(MatchItem {
+            trigger,
+            replace,
+            label,
+        });
+    }
+
+    Ok(match_items)
+}
+
+// Figure out which RDF serialization to parse `path` as. An explicit
+// `--format` flag always wins; otherwise we go by file extension, and if the
+// extension doesn't tell us anything we sniff the first few bytes of the
+// file for an XML declaration or a Turtle/SPARQL-style prefix directive.
+fn resolve_format(path: &Path, format_flag: Option<&str>) -> Result<srdf::RDFFormat, AppError> {
+    let format = if let Some(flag) = format_flag {
+        format_from_name(flag).ok_or_else(|| AppError::UnknownFormat(flag.to_string()))?
+    } else if let Some(format) = path.extension().and_then(|ext| ext.to_str()).and_then(format_from_extension) {
+        format
+    } else {
+        sniff_format(path)?
+    };
+
+    reject_unsupported_format(format)
+}
+
+// srdf 0.1.147's `merge_from_reader` has `RDFFormat::TriG => todo!()`, so
+// letting a recognized-but-unimplemented format through would panic from
+// inside the dependency instead of failing cleanly here.
+fn reject_unsupported_format(format: srdf::RDFFormat) -> Result<srdf::RDFFormat, AppError> {
+    match format {
+        srdf::RDFFormat::TriG => Err(AppError::UnsupportedFormat("TriG".to_string())),
+        other => Ok(other),
+    }
+}
+
+fn format_from_name(name: &str) -> Option<srdf::RDFFormat> {
+    match name.to_lowercase().as_str() {
+        "turtle" | "ttl" => Some(srdf::RDFFormat::Turtle),
+        "ntriples" | "nt" => Some(srdf::RDFFormat::NTriples),
+        "rdfxml" | "rdf" | "owl" | "xml" => Some(srdf::RDFFormat::RDFXML),
+        "trig" => Some(srdf::RDFFormat::TriG),
+        "nquads" | "nq" => Some(srdf::RDFFormat::NQuads),
+        _ => None,
+    }
+}
+
+fn format_from_extension(ext: &str) -> Option<srdf::RDFFormat> {
+    match ext.to_lowercase().as_str() {
+        "ttl" => Some(srdf::RDFFormat::Turtle),
+        "nt" => Some(srdf::RDFFormat::NTriples),
+        "rdf" | "owl" => Some(srdf::RDFFormat::RDFXML),
+        "trig" => Some(srdf::RDFFormat::TriG),
+        "nq" => Some(srdf::RDFFormat::NQuads),
+        _ => None,
+    }
+}
+
+fn sniff_format(path: &Path) -> Result<srdf::RDFFormat, AppError> {
+    let contents = fs::read_to_string(path).map_err(|_| AppError::UnknownFormat("<unreadable file>".to_string()))?;
+    let leading = contents.trim_start();
+    let first_line = leading.lines().next().unwrap_or("");
+
+    if leading.starts_with("<?xml") || leading.starts_with("<rdf:RDF") {
+        return Ok(srdf::RDFFormat::RDFXML);
+    }
+
+    if leading.starts_with("@prefix") || leading.starts_with("PREFIX") || leading.starts_with("prefix") {
+        return Ok(srdf::RDFFormat::Turtle);
+    }
+
+    // N-Triples/N-Quads statements are a bare `<subject> <predicate> <object>
+    // [<graph>] .` on a single line, with no XML declaration and no prefix
+    // directive preceding them — unlike those, a leading `<` alone isn't
+    // conclusive, since it's also legal Turtle.
+    if first_line.starts_with('<') && first_line.trim_end().ends_with(" .") {
+        return Ok(if first_line.matches('<').count() >= 4 {
+            srdf::RDFFormat::NQuads
+        } else {
+            srdf::RDFFormat::NTriples
+        });
+    }
+
+    // Nothing conclusive: keep the historical default rather than failing outright.
+    Ok(srdf::RDFFormat::RDFXML)
+}
+
+// Load `path` and, when `follow_imports` is set, recursively pull in
+// everything reachable through `owl:imports` so entities defined across the
+// import closure (BFO importing RO importing BFO, etc.) are visible to
+// `find_subjects`. A visited-IRI set guards against import cycles.
+fn load_ontology_closure(
+    path: &Path,
+    format: &srdf::RDFFormat,
+    follow_imports: bool,
+    offline: bool,
+    cache_dir: &Path,
+) -> Result<SRDFGraph, AppError> {
+    let mut graph = SRDFGraph::from_path(path, format, None, &srdf::ReaderMode::Lax)?;
+    if matches!(format, srdf::RDFFormat::RDFXML) {
+        graph.merge_prefixes(scan_rdfxml_prefixes(path)?)?;
+    }
+
+    if !follow_imports {
+        return Ok(graph);
+    }
+
+    let owl_import = NamedNode::new("http://www.w3.org/2002/07/owl#imports")?;
+    let mut visited: HashSet<String> = HashSet::new();
+    if let Some(root_iri) = ontology_iri(&graph) {
+        visited.insert(root_iri);
+    }
+
+    let mut pending: Vec<String> = import_iris(&graph, &owl_import)?;
+
+    while let Some(import_iri) = pending.pop() {
+        if !visited.insert(import_iri.clone()) {
+            continue;
+        }
+
+        println!("Following owl:imports -> {}", import_iri);
+
+        let import_path = fetch_import(&import_iri, offline, cache_dir)?;
+        let import_format = resolve_format(&import_path, None)?;
+
+        // Read the import's own owl:imports before merging it in, since the
+        // merged graph no longer lets us tell which triples came from which
+        // document.
+        let imported = SRDFGraph::from_path(&import_path, &import_format, None, &srdf::ReaderMode::Lax)?;
+        pending.extend(import_iris(&imported, &owl_import)?);
+
+        graph.merge_from_path(&import_path, &import_format, None, &srdf::ReaderMode::Lax)?;
+        if matches!(import_format, srdf::RDFFormat::RDFXML) {
+            graph.merge_prefixes(scan_rdfxml_prefixes(&import_path)?)?;
+        }
+    }
+
+    Ok(graph)
+}
+
+// `merge_from_reader`'s RDF/XML branch (unlike its Turtle branch) never
+// captures the `xmlns:` declarations its parser sees, so RDF/XML input —
+// the format BFO, RO and IOF-Core actually ship in — would otherwise come
+// out with no usable prefixes at all. Scan the source document's own
+// `xmlns:prefix="iri"` declarations directly and merge those in instead.
+fn scan_rdfxml_prefixes(path: &Path) -> Result<PrefixMap, AppError> {
+    let contents = fs::read_to_string(path).map_err(|_| AppError::UnknownFormat("<unreadable file>".to_string()))?;
+
+    let mut prefixes: HashMap<&str, &str> = HashMap::new();
+    let mut rest = contents.as_str();
+    while let Some(start) = rest.find("xmlns:") {
+        rest = &rest[start + "xmlns:".len()..];
+        let Some(name_end) = rest.find('=') else { break };
+        let name = rest[..name_end].trim();
+
+        let after_eq = rest[name_end + 1..].trim_start();
+        let Some(quote) = after_eq.chars().next().filter(|c| *c == '"' || *c == '\'') else { continue };
+        let value_start = &after_eq[1..];
+        let Some(value_end) = value_start.find(quote) else { break };
+
+        prefixes.insert(name, &value_start[..value_end]);
+        rest = &value_start[value_end + 1..];
+    }
+
+    Ok(PrefixMap::from_hashmap(prefixes)?)
+}
+
+// The ontology's own IRI, i.e. the subject of its `rdf:type owl:Ontology`
+// triple, used to seed the import-cycle guard so a closure that imports back
+// to the root (BFO importing RO importing BFO) is actually caught.
+fn ontology_iri(graph: &SRDFGraph) -> Option<String> {
+    let rdf_type = NamedNode::new("http://www.w3.org/1999/02/22-rdf-syntax-ns#type").ok()?;
+    let owl_ontology = Term::from(NamedNode::new("http://www.w3.org/2002/07/owl#Ontology").ok()?);
+    graph
+        .triples_with_predicate_object(rdf_type, owl_ontology)
+        .ok()?
+        .find_map(|triple| match triple.subject {
+            NamedOrBlankNode::NamedNode(n) => Some(n.as_str().to_string()),
+            NamedOrBlankNode::BlankNode(_) => None,
+        })
+}
+
+fn import_iris(graph: &SRDFGraph, owl_import: &NamedNode) -> Result<Vec<String>, AppError> {
+    Ok(graph
+        .triples_with_predicate(owl_import.clone())?
+        .filter_map(|triple| match triple.object {
+            Term::NamedNode(n) => Some(n.as_str().to_string()),
+            _ => None,
+        })
+        .collect())
+}
+
+// Resolve an imported IRI to a local file, fetching it over HTTP and caching
+// it on disk (keyed by a sanitized form of the IRI) unless `offline` is set,
+// in which case a missing import is an error rather than a silent skip.
+fn fetch_import(iri: &str, offline: bool, cache_dir: &Path) -> Result<std::path::PathBuf, AppError> {
+    fs::create_dir_all(cache_dir).map_err(|_| AppError::ImportFetchError(iri.to_string()))?;
+
+    let cache_key: String = iri.chars().map(|c| if c.is_alphanumeric() { c } else { '_' }).collect();
+    let cached_path = cache_dir.join(cache_key);
+
+    if cached_path.exists() {
+        return Ok(cached_path);
+    }
+
+    if offline {
+        return Err(AppError::ImportFetchError(iri.to_string()));
+    }
+
+    let body = reqwest::blocking::get(iri)
+        .and_then(|resp| resp.text())
+        .map_err(|_| AppError::ImportFetchError(iri.to_string()))?;
+    fs::write(&cached_path, body).map_err(|_| AppError::ImportFetchError(iri.to_string()))?;
+
+    Ok(cached_path)
+}
+
+// The prefixes the parser saw while reading the graph, not a fixed map.
+fn resolve_prefixmap(graph: &SRDFGraph) -> PrefixMap {
+    graph.prefixmap()
+}
+
 fn get_english_label(labels: &HashSet<Term>) -> Option<&str> {
     for label in labels {
         let literal_content  = match label {
@@ -75,80 +382,223 @@ fn get_english_label(labels: &HashSet<Term>) -> Option<&str> {
     None
 }
 
+// Stop-words skipped when building an acronym, so e.g. "quality of" doesn't
+// contribute a spurious "o".
+const ACRONYM_STOPWORDS: &[&str] = &["of", "the", "a", "an", "and", "or", "in", "on", "for"];
+
 // Return a shortname for the name.
 // If there are multiple words, the first letter of each word is used.
 fn get_shortname(name: String) -> String {
-    todo!()
+    // Letters are lowercased, and words in `ACRONYM_STOPWORDS` contribute none.
+    name.split_whitespace()
+        .map(|word| word.to_lowercase())
+        .filter(|word| !ACRONYM_STOPWORDS.contains(&word.as_str()))
+        .filter_map(|word| word.chars().next())
+        .collect()
+}
+
+// Candidate acronym for an item, extended with the next letter of its
+// longest word when the bare acronym collides with a sibling's.
+fn extend_acronym(label: &str, base: &str) -> Option<String> {
+    let longest_word = label.split_whitespace().max_by_key(|w| w.len())?.to_lowercase();
+    let extra = longest_word.chars().nth(1)?;
+    Some(format!("{}{}", base, extra))
+}
+
+// Group items by candidate acronym, then disambiguate collisions by
+// extending with the next letter of the longest word, falling back to a
+// numeric suffix. `assigned` is shared across every bucket (not reset per
+// bucket) so a bare candidate can't collide with another bucket's extension.
+fn allocate_acronym_triggers(items: &[Item]) -> HashMap<String, String> {
+    let mut buckets: HashMap<String, Vec<(String, String)>> = HashMap::new();
+    for item in items {
+        let label = display_label(item);
+        buckets.entry(get_shortname(label.clone())).or_default().push((item.qualified_name.clone(), label));
+    }
+
+    let mut candidates: Vec<String> = buckets.keys().cloned().collect();
+    candidates.sort();
+
+    let mut assigned: HashSet<String> = HashSet::new();
+    let mut triggers: HashMap<String, String> = HashMap::new();
+    for candidate in candidates {
+        let mut bucket = buckets.remove(&candidate).unwrap();
+        bucket.sort_by(|a, b| a.0.cmp(&b.0));
+
+        for (qualified_name, label) in &bucket {
+            let trigger = if bucket.len() == 1 && !assigned.contains(&candidate) {
+                candidate.clone()
+            } else {
+                let extended = extend_acronym(label, &candidate);
+                match extended {
+                    Some(t) if t != candidate && !assigned.contains(&t) => t,
+                    _ => {
+                        let mut n = 2;
+                        loop {
+                            let numbered = format!("{}-{}", candidate, n);
+                            if !assigned.contains(&numbered) {
+                                break numbered;
+                            }
+                            n += 1;
+                        }
+                    }
+                }
+            };
+            assigned.insert(trigger.clone());
+            triggers.insert(qualified_name.clone(), trigger);
+        }
+    }
+
+    triggers
 }
 
-fn get_bfo_short_number(name: &str) -> Result<i32, AppError> {
-    let tokens: Vec<&str> = name.split("_").collect();
+// Built-in BFO profile: `BFO_0000030` -> `"30"`. `None` if it doesn't fit.
+fn parse_bfo_numeric_suffix(local_name: &str) -> Option<String> {
+    let tokens: Vec<&str> = local_name.split("_").collect();
     if tokens.len() != 2 {
-        return Err(AppError::BfoNameParseError);
+        return None;
     }
-    let number = tokens.get(1).unwrap().parse::<i32>().map_err(|_| AppError::BfoNameParseError)?;
-    Ok(number)
+    tokens.get(1)?.parse::<i32>().ok().map(|n| n.to_string())
 }
 
-fn get_bfo_short_name(label: &str) -> String {
-    label.split(" ").flat_map(|t| t.chars().nth(0)).collect()
+// Falls back to the qualified name rather than aborting on a missing label.
+fn display_label(subject: &Item) -> String {
+    subject.english_label.clone().unwrap_or_else(|| subject.qualified_name.clone())
 }
 
+// The tool's original BFO-only behavior: a numeric-suffix trigger and an
+// acronym trigger.
+fn default_trigger_templates() -> Vec<String> {
+    vec![":{prefix}-{number}".to_string(), ":{prefix}-{acronym}".to_string()]
+}
 
-fn build_bfo_matches(subjects: Vec<Item>, subject_type: SubjectType) -> Result<Vec<MatchItem>, AppError> {
+// `None` if a referenced placeholder (e.g. `{number}`) has no value for this entity.
+fn render_trigger_template(template: &str, prefix: &str, fields: &HashMap<&str, String>) -> Option<String> {
+    let mut rendered = template.replace("{prefix}", prefix);
+    for (field, value) in fields {
+        rendered = rendered.replace(&format!("{{{}}}", field), value);
+    }
+    if rendered.contains('{') {
+        None
+    } else {
+        Some(rendered)
+    }
+}
+
+fn build_matches(subjects: Vec<Item>, subject_type: SubjectType, prefix: &str, templates: &[String], triggers: &HashMap<String, String>) -> Vec<MatchItem> {
     let mut match_items: Vec<MatchItem> = Vec::new();
     for subject in subjects {
         println!("{:#?}", subject);
 
+        let display_label = display_label(&subject).replace(" ", "-");
+
         let label = match subject_type {
-            SubjectType::Class => format!("bfo:{} (Class; {})", subject.english_label.clone().unwrap().replace(" ", "-"), subject.qualified_name.clone()),
-            SubjectType::ObjectProperty => format!("bfo:{} (Object Property; {})", subject.english_label.clone().unwrap().replace(" ", "-"), subject.qualified_name.clone()),
+            SubjectType::Class => format!("{}:{} (Class; {})", prefix, display_label, subject.qualified_name.clone()),
+            SubjectType::ObjectProperty => format!("{}:{} (Object Property; {})", prefix, display_label, subject.qualified_name.clone()),
         };
 
-        // Number-based trigger.
-        // e.g. :bfo-30 for object (http://purl.obolibrary.org/obo/BFO_0000030).
-        let short_number = get_bfo_short_number(&subject.qualified_name)?;
-        let match_item = MatchItem {
-            trigger: format!(":bfo-{}", short_number),
-            replace: subject.qualified_name.clone(),
-            label: label.clone(),
-        };
-        println!("{:#?}", match_item);
-        match_items.push(match_item);
-        let match_item = MatchItem {
-            trigger: format!(":bfo-{}", short_number),
-            replace: format!("bfo:{}", subject.english_label.clone().unwrap().replace(" ", "-")),
-            label: label.clone(),
-        };
-        println!("{:#?}", match_item);
-        match_items.push(match_item);
-
-        // Shortname-based trigger.
-        // e.g. :bfo-obj for object (http://purl.obolibrary.org/obo/BFO_0000030).
-        let short_name = get_bfo_short_name(&subject.english_label.clone().unwrap());
-        let match_item = MatchItem {
-            trigger: format!(":bfo-{}", short_name),
-            replace: subject.qualified_name.clone(),
-            label: label.clone(),
-        };
-        println!("{:#?}", match_item);
-        match_items.push(match_item);
-        let match_item = MatchItem {
-            trigger: format!(":bfo-{}", short_name),
-            replace: format!("bfo:{}", subject.english_label.clone().unwrap().replace(" ", "-")),
-            label: label,
-        };
-        println!("{:#?}", match_item);
-        match_items.push(match_item);
+        let local_name = subject.qualified_name.rsplit(':').next().unwrap_or(&subject.qualified_name);
+        let mut fields: HashMap<&str, String> = HashMap::new();
+        if let Some(number) = parse_bfo_numeric_suffix(local_name) {
+            fields.insert("number", number);
+        }
+        if let Some(acronym) = triggers.get(&subject.qualified_name) {
+            fields.insert("acronym", acronym.clone());
+        }
+
+        for template in templates {
+            let Some(trigger) = render_trigger_template(template, prefix, &fields) else {
+                println!("skipping trigger template {:?} for {} — local name doesn't fit the chosen parser", template, subject.qualified_name);
+                continue;
+            };
+
+            let match_item = MatchItem { trigger: trigger.clone(), replace: subject.qualified_name.clone(), label: label.clone() };
+            println!("{:#?}", match_item);
+            match_items.push(match_item);
+
+            let match_item = MatchItem { trigger, replace: format!("{}:{}", prefix, display_label), label: label.clone() };
+            println!("{:#?}", match_item);
+            match_items.push(match_item);
+        }
     }
-    Ok(match_items)
+    match_items
 }
 
 fn main() {
-    let args: Vec<String> = env::args().collect();
+    let mut args: Vec<String> = env::args().collect();
+
+    // Pull `--query <FILE>` out of the argument list so the remaining
+    // arguments are still the plain <FILE> <PREFIX> pair. When present, it
+    // points at a SPARQL SELECT query file whose bindings (`?trigger`,
+    // `?replace`, `?label`) drive match generation directly, instead of the
+    // hardcoded BFO class/object-property traversal below.
+    let query_path = args.iter().position(|a| a == "--query").map(|i| {
+        let path = args.get(i + 1).cloned().unwrap_or_else(|| {
+            println!("usage: --query requires a <QUERY_FILE> argument");
+            std::process::exit(1);
+        });
+        args.drain(i..=i + 1);
+        path
+    });
+
+    // `--format` overrides autodetection (by extension, then by sniffing the
+    // file's leading bytes) when the tool is pointed at Turtle, N-Triples
+    // or N-Quads ontologies instead of RDF/XML. TriG is recognized but
+    // rejected with `AppError::UnsupportedFormat`; it is not parsed.
+    let format_flag = args.iter().position(|a| a == "--format").map(|i| {
+        let format = args.get(i + 1).cloned().unwrap_or_else(|| {
+            println!("usage: --format requires a <FORMAT> argument");
+            std::process::exit(1);
+        });
+        args.drain(i..=i + 1);
+        format
+    });
+
+    // `--follow-imports` recursively merges everything reachable through
+    // `owl:imports`; `--offline` keeps that resolution restricted to the
+    // local import cache instead of reaching out over HTTP.
+    let follow_imports = args.iter().position(|a| a == "--follow-imports").map(|i| args.remove(i)).is_some();
+    let offline = args.iter().position(|a| a == "--offline").map(|i| args.remove(i)).is_some();
+
+    // `--label-predicate` may be repeated to give an ordered fallback list of
+    // annotation predicates to try for an entity's display label (e.g.
+    // `rdfs:label`, then `skos:prefLabel`, then an iof-core synonym). Falls
+    // back to `default_label_predicates()` when not given at all.
+    let mut label_predicates: Vec<String> = Vec::new();
+    while let Some(i) = args.iter().position(|a| a == "--label-predicate") {
+        let iri = args.get(i + 1).cloned().unwrap_or_else(|| {
+            println!("usage: --label-predicate requires an <IRI> argument");
+            std::process::exit(1);
+        });
+        args.drain(i..=i + 1);
+        label_predicates.push(iri);
+    }
+    if label_predicates.is_empty() {
+        label_predicates = default_label_predicates();
+    }
+
+    // `--trigger-template` may be repeated to supply the trigger patterns to
+    // emit per entity (e.g. `:{prefix}-{number}`, `:{prefix}-{acronym}`).
+    // Falls back to `default_trigger_templates()` — the tool's original
+    // BFO-shaped behavior — when not given at all.
+    let mut trigger_templates: Vec<String> = Vec::new();
+    while let Some(i) = args.iter().position(|a| a == "--trigger-template") {
+        let template = args.get(i + 1).cloned().unwrap_or_else(|| {
+            println!("usage: --trigger-template requires a <TEMPLATE> argument");
+            std::process::exit(1);
+        });
+        args.drain(i..=i + 1);
+        trigger_templates.push(template);
+    }
+    if trigger_templates.is_empty() {
+        trigger_templates = default_trigger_templates();
+    }
 
     if args.len() != 3 {
-        println!("usage: {} <FILE> <PREFIX>", args.get(0).unwrap());
+        println!(
+            "usage: {} <FILE> <PREFIX> [--query <QUERY_FILE>] [--format <FORMAT>] [--follow-imports] [--offline] [--label-predicate <IRI>]... [--trigger-template <TEMPLATE>]...",
+            args.first().unwrap()
+        );
         std::process::exit(1);
     }
 
@@ -163,41 +613,74 @@ fn main() {
         None => std::process::exit(1),
     };
 
-    let graph = SRDFGraph::from_path(path, &srdf::RDFFormat::RDFXML, None, &srdf::ReaderMode::Lax).unwrap();
-    
+    let format = resolve_format(path, format_flag.as_deref()).unwrap();
+    let cache_dir = Path::new(".espanso-ontology-cache");
+
+    let graph = match load_ontology_closure(path, &format, follow_imports, offline, cache_dir) {
+        Ok(graph) => graph,
+        Err(why) => panic!(
+            "{:?}",
+            AppError::GraphParseError { format: format!("{:?}", format), message: format!("{:?}", why) }
+        ),
+    };
+
     println!("Graph's len: {}", graph.len());
 
+    let pm = resolve_prefixmap(&graph);
+
     let owl_import = NamedNode::new("http://www.w3.org/2002/07/owl#imports").unwrap();
     let rdf_type = NamedNode::new("http://www.w3.org/1999/02/22-rdf-syntax-ns#type").unwrap();
     let owl_class = NamedNode::new("http://www.w3.org/2002/07/owl#Class").unwrap();
     let owl_object_property = NamedNode::new("http://www.w3.org/2002/07/owl#ObjectProperty").unwrap();
 
     println!("\n\nImports:");
-    for triple in graph.triples_with_predicate(&owl_import).unwrap() {
+    for triple in graph.triples_with_predicate(owl_import.clone()).unwrap() {
         println!("{}", triple);
     }
 
-    let use_label_when_possible: bool = true;
-
     let mut items: Vec<MatchItem> = Vec::new();
-    let result = find_subjects(&graph, &rdf_type, &Term::from(owl_class));
-    match result {
-        Ok( subjects) => items.append(&mut build_bfo_matches(subjects, SubjectType::Class).unwrap()),
-        Err(_) => panic!("failed to find subjects for Class")
-    }
 
-    let result = find_subjects(&graph, &rdf_type, &Term::from(owl_object_property));
-    match result {
-        Ok( subjects) => items.append(&mut build_bfo_matches(subjects, SubjectType::ObjectProperty).unwrap()),
-        Err(_) => panic!("failed to find subjects for Object Property")
+    if let Some(query_path) = query_path {
+        let query = fs::read_to_string(&query_path)
+            .unwrap_or_else(|why| panic!("couldn't read query file {}: {}", query_path, why));
+        items.append(&mut find_matches_sparql(&graph, &query).unwrap());
+
+        write_matches(items);
+        return;
     }
 
+    let classes = match find_subjects(&graph, &rdf_type, &Term::from(owl_class), &pm, &label_predicates) {
+        Ok(subjects) => subjects,
+        Err(_) => panic!("failed to find subjects for Class"),
+    };
+    let object_properties = match find_subjects(&graph, &rdf_type, &Term::from(owl_object_property), &pm, &label_predicates) {
+        Ok(subjects) => subjects,
+        Err(_) => panic!("failed to find subjects for Object Property"),
+    };
+
+    // Acronyms are allocated across the whole match set up front so that, for
+    // instance, a class and an object property that happen to share a
+    // shortname don't silently shadow one another's espanso trigger.
+    let all_subjects: Vec<Item> = classes
+        .iter()
+        .chain(object_properties.iter())
+        .map(|i| Item { qualified_name: i.qualified_name.clone(), english_label: i.english_label.clone() })
+        .collect();
+    let triggers = allocate_acronym_triggers(&all_subjects);
+
+    items.append(&mut build_matches(classes, SubjectType::Class, prefix, &trigger_templates, &triggers));
+    items.append(&mut build_matches(object_properties, SubjectType::ObjectProperty, prefix, &trigger_templates, &triggers));
+
+    write_matches(items);
+}
+
+fn write_matches(items: Vec<MatchItem>) {
     let out_filepath = Path::new("packages.yml");
-    let out_file = match File::create(&out_filepath) {
+    let out_file = match File::create(out_filepath) {
         Err(why) => panic!("couldn't open {}: {}", out_filepath.display(), why),
         Ok(file) => file,
     };
-    
+
     match serde_yml::to_writer(out_file, &Matches { matches: items }) {
         Err(why) => panic!("couldn't write YAML data: {}", why),
         Ok(_) => println!("Write completed."),
@@ -206,24 +689,76 @@ fn main() {
 
 #[derive(Debug)]
 enum AppError {
-    AppError,
-    BfoNameParseError,
+    Other,
+    SparqlMissingBinding(&'static str),
+    SparqlQueryError(String),
+    UnknownFormat(String),
+    UnsupportedFormat(String),
+    GraphParseError { format: String, message: String },
+    ImportFetchError(String),
+}
+
+impl std::fmt::Display for AppError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AppError::Other => write!(f, "unexpected RDF error"),
+            AppError::SparqlMissingBinding(var) => write!(f, "SPARQL result is missing expected binding ?{}", var),
+            AppError::SparqlQueryError(message) => write!(f, "SPARQL query failed: {}", message),
+            AppError::UnknownFormat(name) => write!(f, "unknown RDF format {:?}", name),
+            AppError::UnsupportedFormat(name) => write!(f, "{} is a recognized RDF format, but this build can't parse it yet", name),
+            AppError::GraphParseError { format, message } => write!(f, "failed to parse graph as {}: {}", format, message),
+            AppError::ImportFetchError(iri) => write!(f, "failed to fetch import {}", iri),
+        }
+    }
 }
 
 impl From<PrefixMapError> for AppError {
     fn from(_: PrefixMapError) -> Self {
-        AppError::AppError
+        AppError::Other
     }
 }
 
 impl From<IriParseError> for AppError {
     fn from(_: IriParseError) -> Self {
-        AppError::AppError
+        AppError::Other
     }
 }
 
 impl From<SRDFGraphError> for AppError {
     fn from(_: SRDFGraphError) -> Self {
-        AppError::AppError
+        AppError::Other
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn item(qualified_name: &str, label: &str) -> Item {
+        Item { qualified_name: qualified_name.to_string(), english_label: Some(label.to_string()) }
+    }
+
+    #[test]
+    fn allocate_acronym_triggers_has_no_collisions() {
+        let items = vec![
+            item("bfo:material_entity", "material entity"),
+            item("bfo:material_artifact", "material artifact"),
+            item("bfo:mass_abstract", "mass abstract"),
+            item("bfo:quality_of_motion", "quality of motion"),
+            // Extending "mass abstract" against its sibling "material artifact"
+            // lands on "mab", which this bucket-of-one's own raw candidate
+            // also produces — a cross-bucket collision.
+            item("bfo:mass_abstract_boundary", "Mass Abstract Boundary"),
+        ];
+
+        let triggers = allocate_acronym_triggers(&items);
+
+        assert_eq!(triggers.len(), items.len());
+
+        let mut seen = HashSet::new();
+        for item in &items {
+            let trigger = triggers.get(&item.qualified_name).expect("every item gets a trigger");
+            assert!(seen.insert(trigger.clone()), "duplicate trigger {:?} for {}", trigger, item.qualified_name);
+        }
     }
 }
\ No newline at end of file